@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use {CH16Header, CHIP16, MEMORY};
+use sound::Sound;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Truncated,
+    BadMagic,
+    TooLarge,
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+/// A cursor over cartridge bytes that never indexes out of range; every
+/// read returns `LoadError::Truncated` instead of panicking.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data: data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        if self.pos + len > self.data.len() {
+            return Err(LoadError::Truncated);
+        }
+
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(try!(self.take(1))[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(LittleEndian::read_u16(try!(self.take(2))))
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        Ok(LittleEndian::read_u32(try!(self.take(4))))
+    }
+}
+
+/// CRC-32 (IEEE 802.3), computed bit-by-bit so we don't need a lookup
+/// table just to check one cartridge header field.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFFFFFF;
+
+    for &byte in data {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+
+    !crc
+}
+
+/// Loads and validates a `.c16` cartridge: checks the `"CH16"` magic,
+/// rejects a `size` that would overflow `MEMORY`, and reports (without
+/// failing) a CRC32 mismatch against the stored header value.
+pub fn load_cartridge(path: &Path, sound: Sound) -> Result<CHIP16, LoadError> {
+    let mut file = try!(File::open(path));
+    let mut cartridge = Vec::new();
+    try!(file.read_to_end(&mut cartridge));
+
+    let mut reader = Reader::new(&cartridge);
+
+    let magic = try!(reader.take(4));
+    if magic != b"CH16" {
+        return Err(LoadError::BadMagic);
+    }
+
+    let reserved = try!(reader.u8());
+    let version = try!(reader.u8());
+    let size = try!(reader.u32());
+    let start = try!(reader.u16());
+    let crc = try!(reader.u32());
+
+    if size as usize > MEMORY {
+        return Err(LoadError::TooLarge);
+    }
+
+    let body = try!(reader.take(size as usize));
+
+    let computed = crc32(body);
+    if computed != crc {
+        println!("warning: {:?}: CRC32 mismatch (header {:#010X}, computed {:#010X})",
+                 path,
+                 crc,
+                 computed);
+    }
+
+    let header = CH16Header {
+        magic: "CH16".to_string(),
+        reserved: reserved,
+        version: version,
+        size: size,
+        start: start,
+        crc32: crc,
+    };
+
+    Ok(CHIP16::new(&header, body, sound))
+}
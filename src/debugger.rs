@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use {CHIP16, State, MEMORY};
+
+/// Exposes the next instruction's disassembly without executing it, so
+/// the debugger (and anything else that wants to peek ahead) doesn't
+/// have to duplicate `cycle`'s decode step.
+pub trait Debuggable {
+    fn disassemble(&self) -> String;
+}
+
+impl Debuggable for CHIP16 {
+    fn disassemble(&self) -> String {
+        let instr = &self.memory[self.pc as usize..self.pc as usize + 4];
+        let opcode = instr[0];
+
+        let ll: u16 = instr[2] as u16;
+        let hh: u16 = instr[3] as u16;
+        let hhll: u16 = hh << 8 | ll;
+
+        let x = instr[1] & 0x0F;
+        let y = (instr[1] & 0xF0) >> 4;
+        let z = instr[2] & 0x0F;
+
+        match opcode {
+            0x00 => format!("NOP"),
+            0x01 => format!("CLS"),
+            0x02 => format!("VBLNK"),
+            0x03 => format!("BGC {:X}", instr[2] & 0x0F),
+            0x04 => format!("SPR w:{} h:{}", instr[2], instr[3]),
+            0x05 => format!("DRW R{:X}, R{:X}, {:#X}", x, y, hhll),
+            0x09 => format!("SND0"),
+            0x0A => format!("SND1 {:#X}", hhll),
+            0x0B => format!("SND2 {:#X}", hhll),
+            0x0C => format!("SND3 {:#X}", hhll),
+            0x0E => format!("SNG {:#X}, {:#X}, {:#X}", instr[1], instr[2], instr[3]),
+            0x10 => format!("JMP {:#X}", hhll),
+            0x12 => format!("J{:X} {:#X}", x, hhll),
+            0x13 => format!("JME R{:X}, R{:X}, {:#X}", x, y, hhll),
+            0x20 => format!("LDI R{:X}, {:#X}", x, hhll),
+            0x22 => format!("LDM R{:X}, {:#X}", x, hhll),
+            0x23 => format!("LDM R{:X}, R{:X}", x, y),
+            0x24 => format!("MOV R{:X}, R{:X}", x, y),
+            0x41 => format!("ADD R{:X}, R{:X}", x, y),
+            0x50 => format!("SUB R{:X}, {:#X}", x, hhll as i16),
+            0x51 => format!("SUB R{:X}, R{:X}", x, y),
+            0x52 => format!("SUB R{:X}, R{:X}, R{:X}", x, y, z),
+            _ => format!("DB {:#04X}", opcode),
+        }
+    }
+}
+
+/// A command-driven monitor attached to a `CHIP16` instance: breakpoints
+/// on `pc`, single-stepping, register/memory dumps and a per-instruction
+/// trace that the `trace` command toggles (off by default). `cpu_loop` calls `before_cycle` ahead of every
+/// `cycle`; while disabled it's a single `bool` check with no other cost.
+pub struct Debugger {
+    enabled: bool,
+    trace_only: bool,
+    breakpoints: HashSet<u16>,
+    running: bool,
+    steps_remaining: u32,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            enabled: false,
+            trace_only: false,
+            breakpoints: HashSet::new(),
+            running: true,
+            steps_remaining: 0,
+        }
+    }
+
+    pub fn enable(&mut self) {
+        self.enabled = true;
+        self.running = false;
+    }
+
+    /// Called before every `cycle`. Returns `State::Stop` if the user
+    /// quit the debugger, otherwise `State::Continue`.
+    pub fn before_cycle(&mut self, chip: &CHIP16) -> State {
+        if !self.enabled {
+            return State::Continue;
+        }
+
+        if self.trace_only {
+            println!("{:#06X}: {}", chip.pc, chip.disassemble());
+        }
+
+        if self.breakpoints.contains(&chip.pc) {
+            println!("breakpoint hit at {:#06X}", chip.pc);
+            self.running = false;
+            self.steps_remaining = 0;
+        }
+
+        if self.steps_remaining > 0 {
+            self.steps_remaining -= 1;
+
+            if !self.trace_only {
+                println!("{:#06X}: {}", chip.pc, chip.disassemble());
+            }
+
+            return State::Continue;
+        }
+
+        if self.running {
+            return State::Continue;
+        }
+
+        self.prompt(chip)
+    }
+
+    fn prompt(&mut self, chip: &CHIP16) -> State {
+        loop {
+            print!("(chip16-dbg) ");
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                return State::Stop;
+            }
+
+            let mut parts = line.trim().split_whitespace();
+            let command = parts.next();
+
+            match command {
+                Some("b") | Some("break") => {
+                    match parts.next().and_then(parse_addr) {
+                        Some(addr) => {
+                            self.breakpoints.insert(addr);
+                            println!("breakpoint set at {:#06X}", addr);
+                        }
+                        None => println!("usage: break <addr>"),
+                    }
+                }
+                Some("cl") | Some("clear") => {
+                    match parts.next().and_then(parse_addr) {
+                        Some(addr) => {
+                            self.breakpoints.remove(&addr);
+                            println!("breakpoint cleared at {:#06X}", addr);
+                        }
+                        None => println!("usage: clear <addr>"),
+                    }
+                }
+                Some("s") | Some("step") => {
+                    let count: u32 = parts.next().and_then(|s| s.parse().ok()).unwrap_or(1);
+
+                    println!("{:#06X}: {}", chip.pc, chip.disassemble());
+                    self.steps_remaining = count.saturating_sub(1);
+
+                    return State::Continue;
+                }
+                Some("c") | Some("continue") => {
+                    self.running = true;
+                    return State::Continue;
+                }
+                Some("r") | Some("regs") => self.dump_registers(chip),
+                Some("m") | Some("mem") => {
+                    let start = parts.next().and_then(parse_addr).unwrap_or(chip.pc);
+                    let len = parts.next().and_then(|s| s.parse().ok()).unwrap_or(64u16);
+
+                    self.dump_memory(chip, start, len);
+                }
+                Some("t") | Some("trace") => {
+                    self.trace_only = !self.trace_only;
+                    println!("trace_only = {}", self.trace_only);
+                }
+                Some("q") | Some("quit") => return State::Stop,
+                _ => {
+                    println!("commands: break <addr>, clear <addr>, step [n], continue, regs, \
+                               mem <addr> [len], trace, quit");
+                }
+            }
+        }
+    }
+
+    fn dump_registers(&self, chip: &CHIP16) {
+        for (i, r) in chip.regs.iter().enumerate() {
+            print!("R{:X}={:#06X} ", i, *r as u16);
+
+            if i % 4 == 3 {
+                println!("");
+            }
+        }
+
+        println!("PC={:#06X} SP={:#06X} FLAGS={:#04X}",
+                  chip.pc,
+                  chip.sp,
+                  chip.flags.bits());
+    }
+
+    fn dump_memory(&self, chip: &CHIP16, start: u16, len: u16) {
+        let start = start as usize;
+        let end = (start + len as usize).min(MEMORY);
+
+        for (row, chunk) in chip.memory[start..end].chunks(16).enumerate() {
+            print!("{:#06X}: ", start + row * 16);
+
+            for b in chunk {
+                print!("{:02X} ", b);
+            }
+
+            println!("");
+        }
+    }
+}
+
+fn parse_addr(s: &str) -> Option<u16> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
@@ -0,0 +1,103 @@
+use byteorder::{ByteOrder, LittleEndian};
+use minifb::{Key, Window};
+
+use {CHIP16, IO_ADDR};
+
+pub const BTN_UP: u16 = 0x0001;
+pub const BTN_DOWN: u16 = 0x0002;
+pub const BTN_LEFT: u16 = 0x0004;
+pub const BTN_RIGHT: u16 = 0x0008;
+pub const BTN_SELECT: u16 = 0x0010;
+pub const BTN_START: u16 = 0x0020;
+pub const BTN_A: u16 = 0x0040;
+pub const BTN_B: u16 = 0x0080;
+
+/// Memory-mapped controller state words, one per player, at the `IO_ADDR`
+/// region reserved for input.
+pub const CONTROLLER_0_ADDR: u16 = IO_ADDR;
+pub const CONTROLLER_1_ADDR: u16 = IO_ADDR + 2;
+
+/// Which physical key drives each button, so players can rebind either
+/// controller instead of being stuck with the defaults.
+#[derive(Clone)]
+pub struct KeyMap {
+    pub up: Key,
+    pub down: Key,
+    pub left: Key,
+    pub right: Key,
+    pub select: Key,
+    pub start: Key,
+    pub a: Key,
+    pub b: Key,
+}
+
+impl KeyMap {
+    pub fn player_one() -> KeyMap {
+        KeyMap {
+            up: Key::Up,
+            down: Key::Down,
+            left: Key::Left,
+            right: Key::Right,
+            select: Key::RightShift,
+            start: Key::Enter,
+            a: Key::X,
+            b: Key::Z,
+        }
+    }
+
+    pub fn player_two() -> KeyMap {
+        KeyMap {
+            up: Key::W,
+            down: Key::S,
+            left: Key::A,
+            right: Key::D,
+            select: Key::Tab,
+            start: Key::Space,
+            a: Key::K,
+            b: Key::J,
+        }
+    }
+
+    /// Polls `minifb`'s current key state and packs it into the
+    /// Up/Down/Left/Right/Select/Start/A/B bitmask the cartridge expects.
+    pub fn poll(&self, window: &Window) -> u16 {
+        let mut state = 0u16;
+
+        if window.is_key_down(self.up) {
+            state |= BTN_UP;
+        }
+        if window.is_key_down(self.down) {
+            state |= BTN_DOWN;
+        }
+        if window.is_key_down(self.left) {
+            state |= BTN_LEFT;
+        }
+        if window.is_key_down(self.right) {
+            state |= BTN_RIGHT;
+        }
+        if window.is_key_down(self.select) {
+            state |= BTN_SELECT;
+        }
+        if window.is_key_down(self.start) {
+            state |= BTN_START;
+        }
+        if window.is_key_down(self.a) {
+            state |= BTN_A;
+        }
+        if window.is_key_down(self.b) {
+            state |= BTN_B;
+        }
+
+        state
+    }
+}
+
+impl CHIP16 {
+    /// Writes a polled controller bitmask into the memory-mapped I/O
+    /// word for `player` (0 or 1), where `LDM`/`LDM Rx,Ry` read it from.
+    pub fn write_controller(&mut self, player: usize, state: u16) {
+        let addr = if player == 0 { CONTROLLER_0_ADDR } else { CONTROLLER_1_ADDR } as usize;
+
+        LittleEndian::write_u16(&mut self.memory[addr..addr + 2], state);
+    }
+}
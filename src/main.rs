@@ -4,14 +4,22 @@ extern crate byteorder;
 extern crate rustc_serialize;
 extern crate minifb;
 extern crate clock_ticks;
+extern crate cpal;
 
-use std::fs::File;
-use std::io::Read;
+mod state;
+mod sound;
+mod input;
+mod debugger;
+mod cartridge;
 
 use rustc_serialize::hex::ToHex;
 
 use byteorder::{ByteOrder, LittleEndian};
 
+use sound::{Envelope, Sound};
+use input::KeyMap;
+use debugger::Debugger;
+
 #[derive(Debug)]
 struct CH16Header {
     magic: String,
@@ -22,19 +30,6 @@ struct CH16Header {
     crc32: u32,
 }
 
-impl<'a> From<&'a [u8]> for CH16Header {
-    fn from(val: &[u8]) -> CH16Header {
-        CH16Header {
-            magic: String::from_utf8(val[..0x04].to_vec()).unwrap(),
-            reserved: val[0x04],
-            version: val[0x05],
-            size: LittleEndian::read_u32(&val[0x06..0x0A]),
-            start: LittleEndian::read_u16(&val[0x0A..0x0C]),
-            crc32: LittleEndian::read_u32(&val[0x0C..0x10]),
-        }
-    }
-}
-
 #[allow(dead_code)]
 bitflags! {
     flags Flags: u8 {
@@ -47,7 +42,6 @@ bitflags! {
 }
 
 const STACK_START: u16 = 0xFDF0;
-#[allow(dead_code)]
 const IO_ADDR: u16 = 0xFFF0;
 const MEMORY: usize = 0xFFFF;
 
@@ -68,6 +62,15 @@ struct CHIP16 {
     spriteh: u8,
 
     vblank: bool,
+
+    sound: Sound,
+    envelope: Envelope,
+
+    palette: Palette,
+    rng_state: u32,
+
+    hflip: bool,
+    vflip: bool,
 }
 
 #[allow(dead_code)]
@@ -99,24 +102,93 @@ pub enum Color {
 
 impl From<u8> for Color {
     fn from(val: u8) -> Color {
-        match val {
-            0xF => Color::White,
-            _ => Color::Transparent,
+        match val & 0x0F {
+            0x0 => Color::Transparent,
+            0x1 => Color::Black,
+            0x2 => Color::Gray,
+            0x3 => Color::Red,
+            0x4 => Color::Pink,
+            0x5 => Color::DarkBrown,
+            0x6 => Color::Brown,
+            0x7 => Color::Orange,
+            0x8 => Color::Yelow,
+            0x9 => Color::Green,
+            0xA => Color::LightGreen,
+            0xB => Color::DarkBlue,
+            0xC => Color::Blue,
+            0xD => Color::LightBlue,
+            0xE => Color::SkyBlue,
+            _ => Color::White,
         }
     }
 }
 
-impl Into<u32> for Color {
-    fn into(self: Color) -> u32 {
-        match self {
-            Color::White => 0xFFFFFFFF,
-            _ => 0x00000000,
+/// Chip-16's default 16-entry palette (0xAARRGGBB), indexed the same way
+/// as `Color`'s variants so `PAL`-loaded palettes and `Color::from` agree
+/// on what index N means.
+const DEFAULT_PALETTE: [u32; 16] = [0x00000000, 0xFF000000, 0xFF757575, 0xFFBF3932,
+                                     0xFFDE7AAE, 0xFF4C3D21, 0xFF905F25, 0xFFE49452,
+                                     0xFFEAD979, 0xFF537A3D, 0xFF6ABE30, 0xFF1B2632,
+                                     0xFF2B4F81, 0xFF5B6EE1, 0xFF639BFF, 0xFFFFFFFF];
+
+#[derive(Clone)]
+struct Palette {
+    colors: [u32; 16],
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette { colors: DEFAULT_PALETTE }
+    }
+}
+
+impl Palette {
+    /// Loads 16 RGB triples (48 bytes) as set by the `PAL` instruction.
+    fn load(&mut self, rgb: &[u8]) {
+        for (i, chunk) in rgb.chunks(3).take(16).enumerate() {
+            let r = chunk[0] as u32;
+            let g = chunk[1] as u32;
+            let b = chunk[2] as u32;
+
+            self.colors[i] = 0xFF000000 | (r << 16) | (g << 8) | b;
         }
     }
+
+    fn resolve(&self, color: &Color) -> u32 {
+        self.colors[color.clone() as usize]
+    }
+}
+
+/// Evaluates a Chip-16 condition code (as used by `Jcc`/`Ccc`) against
+/// the current flags: Z, NZ, N, NN, P, O, NO, A, AE, B, BE, G, GE, L, LE.
+fn condition_met(flags: Flags, cc: u8) -> bool {
+    let carry = (flags & CARRY) == CARRY;
+    let zero = (flags & ZERO) == ZERO;
+    let neg = (flags & NEGATIVE) == NEGATIVE;
+    let ovf = (flags & OVERFLOW) == OVERFLOW;
+
+    match cc {
+        0x0 => zero,
+        0x1 => !zero,
+        0x2 => neg,
+        0x3 => !neg,
+        0x4 => !neg && !zero,
+        0x5 => ovf,
+        0x6 => !ovf,
+        0x7 => !carry && !zero,
+        0x8 => !carry,
+        0x9 => carry,
+        0xA => carry || zero,
+        0xB => !zero && (neg == ovf),
+        0xC => neg == ovf,
+        0xD => neg != ovf,
+        0xE => zero || (neg != ovf),
+        _ => false,
+    }
 }
 
 impl CHIP16 {
-    fn new(header: &CH16Header, cart: &[u8]) -> CHIP16 {
+    fn new(header: &CH16Header, cart: &[u8], sound: Sound) -> CHIP16 {
         let mut ret = CHIP16 {
             memory: [0; MEMORY],
             pc: header.start,
@@ -128,11 +200,16 @@ impl CHIP16 {
             spritew: 0,
             spriteh: 0,
             vblank: false,
+            sound: sound,
+            envelope: Envelope::default(),
+            palette: Palette::default(),
+            rng_state: (clock_ticks::precise_time_ns() as u32) | 1,
+            hflip: false,
+            vflip: false,
         };
 
-        for i in 0..header.size {
-            ret.memory[i as usize] = cart[i as usize];
-        }
+        let size = header.size as usize;
+        ret.memory[..size].copy_from_slice(&cart[..size]);
 
         ret
     }
@@ -145,7 +222,6 @@ impl CHIP16 {
         let ll: u16 = instr[2] as u16;
         let hh: u16 = instr[3] as u16;
         let hhll: u16 = hh << 8 | ll;
-        let val = self.memory[hhll as usize];
 
         let x = instr[1] & 0x0F;
         let y = (instr[1] & 0xF0) >> 4;
@@ -161,9 +237,10 @@ impl CHIP16 {
                 self.bg = Color::Transparent;
 
                 let mut buff = screen.lock().unwrap();
+                let bg = self.palette.resolve(&self.bg);
 
                 for i in buff.iter_mut() {
-                    *i = self.bg.clone().into();
+                    *i = bg;
                 }
 
                 // println!("CLS");
@@ -195,29 +272,104 @@ impl CHIP16 {
             0x05 => {
                 let mut buff = screen.lock().unwrap();
 
-                let mut xpos = rx as i16;
-                let mut ypos = ry as i16;
-                let mut addr = hhll as usize;
+                let base_x = rx as i32;
+                let base_y = ry as i32;
+                let bg_color = self.palette.resolve(&self.bg);
+                let mut collided = false;
 
                 // println!("DRW R{:X}, R{:X}, {:#X}", x, y, hhll);
 
-                for j in 0..self.spriteh {
-                    ypos += j as i16;
-                    for i in 0..self.spritew {
-                        let color = self.memory[addr];
-                        let left: Color = ((color & 0xF0) >> 4 as u8).into();
-                        let right: Color = ((color & 0x0F) as u8).into();
-                        let pos = (xpos as i64 + ypos as i64 * WIDTH as i64) as usize;
+                for row in 0..self.spriteh as i32 {
+                    let src_row = if self.vflip { self.spriteh as i32 - 1 - row } else { row };
+                    let row_addr = hhll as usize + src_row as usize * self.spritew as usize;
 
-                        buff[pos + 0] = left.into();
-                        buff[pos + 1] = right.into();
+                    let mut pixels: Vec<u8> = Vec::with_capacity(self.spritew as usize * 2);
+                    for col in 0..self.spritew as usize {
+                        let byte = self.memory[row_addr + col];
+                        pixels.push((byte & 0xF0) >> 4);
+                        pixels.push(byte & 0x0F);
+                    }
 
-                        addr += (i * j + self.spritew as u8) as usize;
-                        xpos += i as i16 * 2;
+                    if self.hflip {
+                        pixels.reverse();
+                    }
 
-                        // TODO: Check collision
+                    for (col, &idx) in pixels.iter().enumerate() {
+                        // Index 0 is transparent: skip it rather than
+                        // drawing over whatever's already there.
+                        if idx == 0 {
+                            continue;
+                        }
+
+                        let px = base_x + col as i32;
+                        let py = base_y + row;
+
+                        // Clip instead of wrapping past the framebuffer.
+                        if px < 0 || py < 0 || px as usize >= WIDTH || py as usize >= HEIGHT {
+                            continue;
+                        }
+
+                        let pos = py as usize * WIDTH + px as usize;
+
+                        if buff[pos] != bg_color {
+                            collided = true;
+                        }
+
+                        buff[pos] = self.palette.resolve(&Color::from(idx));
                     }
                 }
+
+                if collided {
+                    self.flags |= CARRY;
+                } else {
+                    self.flags &= !CARRY;
+                }
+            }
+            0x07 => {
+                self.rng_state ^= self.rng_state << 13;
+                self.rng_state ^= self.rng_state >> 17;
+                self.rng_state ^= self.rng_state << 5;
+
+                let max = hhll as u32 + 1;
+                self.regs[x as usize] = (self.rng_state % max) as i16;
+
+                // println!("RND R{:X}, {:#X}", x, hhll);
+            }
+            0x08 => {
+                let m = instr[3];
+                self.hflip = (m & 0x2) != 0;
+                self.vflip = (m & 0x1) != 0;
+
+                // println!("FLIP {:#X}", m);
+            }
+            0x09 => {
+                self.sound.stop();
+
+                // println!("SND0");
+            }
+            0x0A => {
+                self.sound.play(hhll, 500, self.envelope);
+
+                // println!("SND1 {:#X}", hhll);
+            }
+            0x0B => {
+                self.sound.play(hhll, 1000, self.envelope);
+
+                // println!("SND2 {:#X}", hhll);
+            }
+            0x0C => {
+                self.sound.play(hhll, 1500, self.envelope);
+
+                // println!("SND3 {:#X}", hhll);
+            }
+            0x0E => {
+                let ad = instr[1];
+                let vt = instr[2];
+                let sr = instr[3];
+
+                self.envelope = Envelope::from_registers(ad, vt, sr);
+
+                // println!("SNG {:#X}, {:#X}, {:#X}", ad, vt, sr);
             }
             0x10 => {
                 self.pc = hhll;
@@ -225,23 +377,11 @@ impl CHIP16 {
                 // println!("JMP {:#X}", hhll);
             }
             0x12 => {
-                match x {
-                    0x00 => {
-                        if (self.flags & ZERO) == ZERO {
-                            self.pc = hhll;
-                        }
-
-                        // println!("JZ {:#X}", hhll)
-                    }
-                    0x09 => {
-                        if (self.flags & CARRY) == CARRY {
-                            self.pc = hhll;
-                        }
-
-                        // println!("JB {:#X}", hhll)
-                    }
-                    _ => panic!("J{:x} {:#X}", x, hhll),
+                if condition_met(self.flags, x) {
+                    self.pc = hhll;
                 }
+
+                // println!("J{:X} {:#X}", x, hhll);
             }
             0x13 => {
                 if rx == ry {
@@ -250,11 +390,50 @@ impl CHIP16 {
 
                 // println!("JME R{:X}, R{:X}, {:#X}", x, y, hhll);
             }
+            0x14 => {
+                let ret_addr = self.pc;
+                self.push_u16(ret_addr);
+                self.pc = hhll;
+
+                // println!("CALL {:#X}", hhll);
+            }
+            0x15 => {
+                self.pc = self.pop_u16();
+
+                // println!("RET");
+            }
+            0x16 => {
+                self.pc = rx as u16;
+
+                // println!("JMP R{:X}", x);
+            }
+            0x17 => {
+                if condition_met(self.flags, x) {
+                    let ret_addr = self.pc;
+                    self.push_u16(ret_addr);
+                    self.pc = hhll;
+                }
+
+                // println!("C{:X} {:#X}", x, hhll);
+            }
             0x20 => {
                 self.regs[x as usize] = hhll as i16;
 
                 // println!("LDI R{:X}, {:#X}", x, hhll);
             }
+            0x22 => {
+                let word = LittleEndian::read_u16(&self.memory[hhll as usize..hhll as usize + 2]);
+                self.regs[x as usize] = word as i16;
+
+                // println!("LDM R{:X}, {:#X}", x, hhll);
+            }
+            0x23 => {
+                let addr = ry as u16 as usize;
+                let word = LittleEndian::read_u16(&self.memory[addr..addr + 2]);
+                self.regs[x as usize] = word as i16;
+
+                // println!("LDM R{:X}, R{:X}", x, y);
+            }
             0x24 => {
                 self.regs[x as usize] = ry;
 
@@ -381,6 +560,279 @@ impl CHIP16 {
                     self.flags &= !ZERO;
                 }
             }
+            0x60 => {
+                let res = rx & hhll as i16;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("ANDI R{:X}, {:#X}", x, hhll);
+            }
+            0x61 => {
+                let res = rx & ry;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("AND R{:X}, R{:X}", x, y);
+            }
+            0x62 => {
+                let res = rx & ry;
+                self.regs[z as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("AND R{:X}, R{:X}, R{:X}", x, y, z);
+            }
+            0x70 => {
+                let res = rx | hhll as i16;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("ORI R{:X}, {:#X}", x, hhll);
+            }
+            0x71 => {
+                let res = rx | ry;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("OR R{:X}, R{:X}", x, y);
+            }
+            0x72 => {
+                let res = rx | ry;
+                self.regs[z as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("OR R{:X}, R{:X}, R{:X}", x, y, z);
+            }
+            0x80 => {
+                let res = rx ^ hhll as i16;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("XORI R{:X}, {:#X}", x, hhll);
+            }
+            0x81 => {
+                let res = rx ^ ry;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("XOR R{:X}, R{:X}", x, y);
+            }
+            0x82 => {
+                let res = rx ^ ry;
+                self.regs[z as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("XOR R{:X}, R{:X}, R{:X}", x, y, z);
+            }
+            0x90 => {
+                let product = (rx as u16 as u32) * (hhll as u32);
+                let res = product as u16 as i16;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                if product > 0xFFFF {
+                    self.flags |= CARRY;
+                } else {
+                    self.flags &= !CARRY;
+                }
+
+                // println!("MULI R{:X}, {:#X}", x, hhll);
+            }
+            0x91 => {
+                let product = (rx as u16 as u32) * (ry as u16 as u32);
+                let res = product as u16 as i16;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                if product > 0xFFFF {
+                    self.flags |= CARRY;
+                } else {
+                    self.flags &= !CARRY;
+                }
+
+                // println!("MUL R{:X}, R{:X}", x, y);
+            }
+            0x92 => {
+                let product = (rx as u16 as u32) * (ry as u16 as u32);
+                let res = product as u16 as i16;
+                self.regs[z as usize] = res;
+                self.update_logic_flags(res);
+
+                if product > 0xFFFF {
+                    self.flags |= CARRY;
+                } else {
+                    self.flags &= !CARRY;
+                }
+
+                // println!("MUL R{:X}, R{:X}, R{:X}", x, y, z);
+            }
+            0xA0 => {
+                let divisor = hhll as i16;
+                let (res, rem_nonzero) = if divisor == 0 {
+                    (0, false)
+                } else {
+                    (rx.wrapping_div(divisor), rx.wrapping_rem(divisor) != 0)
+                };
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                if rem_nonzero {
+                    self.flags |= CARRY;
+                } else {
+                    self.flags &= !CARRY;
+                }
+
+                // println!("DIVI R{:X}, {:#X}", x, hhll);
+            }
+            0xA1 => {
+                let (res, rem_nonzero) = if ry == 0 {
+                    (0, false)
+                } else {
+                    (rx.wrapping_div(ry), rx.wrapping_rem(ry) != 0)
+                };
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                if rem_nonzero {
+                    self.flags |= CARRY;
+                } else {
+                    self.flags &= !CARRY;
+                }
+
+                // println!("DIV R{:X}, R{:X}", x, y);
+            }
+            0xA2 => {
+                let (res, rem_nonzero) = if ry == 0 {
+                    (0, false)
+                } else {
+                    (rx.wrapping_div(ry), rx.wrapping_rem(ry) != 0)
+                };
+                self.regs[z as usize] = res;
+                self.update_logic_flags(res);
+
+                if rem_nonzero {
+                    self.flags |= CARRY;
+                } else {
+                    self.flags &= !CARRY;
+                }
+
+                // println!("DIV R{:X}, R{:X}, R{:X}", x, y, z);
+            }
+            0xA3 => {
+                let divisor = hhll as i16;
+                let res = if divisor == 0 { 0 } else { rx.wrapping_rem(divisor) };
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("MODI R{:X}, {:#X}", x, hhll);
+            }
+            0xA4 => {
+                let res = if ry == 0 { 0 } else { rx.wrapping_rem(ry) };
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("MOD R{:X}, R{:X}", x, y);
+            }
+            0xA5 => {
+                let res = if ry == 0 { 0 } else { rx.wrapping_rem(ry) };
+                self.regs[z as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("MOD R{:X}, R{:X}, R{:X}", x, y, z);
+            }
+            0xB0 => {
+                let n = z as u32;
+                let res = (rx as u16).wrapping_shl(n) as i16;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("SHL R{:X}, {}", x, n);
+            }
+            0xB1 => {
+                let n = z as u32;
+                let res = (rx as u16).wrapping_shr(n) as i16;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("SHR R{:X}, {}", x, n);
+            }
+            0xB2 => {
+                let n = z as u32;
+                let res = rx.wrapping_shr(n);
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("SAR R{:X}, {}", x, n);
+            }
+            0xB3 => {
+                let n = (ry as u16 & 0xF) as u32;
+                let res = (rx as u16).wrapping_shl(n) as i16;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("SHL R{:X}, R{:X}", x, y);
+            }
+            0xB4 => {
+                let n = (ry as u16 & 0xF) as u32;
+                let res = (rx as u16).wrapping_shr(n) as i16;
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("SHR R{:X}, R{:X}", x, y);
+            }
+            0xB5 => {
+                let n = (ry as u16 & 0xF) as u32;
+                let res = rx.wrapping_shr(n);
+                self.regs[x as usize] = res;
+                self.update_logic_flags(res);
+
+                // println!("SAR R{:X}, R{:X}", x, y);
+            }
+            0xC0 => {
+                self.push_u16(rx as u16);
+
+                // println!("PUSH R{:X}", x);
+            }
+            0xC1 => {
+                let val = self.pop_u16();
+                self.regs[x as usize] = val as i16;
+
+                // println!("POP R{:X}", x);
+            }
+            0xC2 => {
+                for i in 0..16 {
+                    let r = self.regs[i];
+                    self.push_u16(r as u16);
+                }
+
+                // println!("PUSHALL");
+            }
+            0xC3 => {
+                for i in (0..16).rev() {
+                    let val = self.pop_u16();
+                    self.regs[i] = val as i16;
+                }
+
+                // println!("POPALL");
+            }
+            0xC4 => {
+                let flags = self.flags.bits() as u16;
+                self.push_u16(flags);
+
+                // println!("PUSHF");
+            }
+            0xC5 => {
+                let val = self.pop_u16();
+                self.flags = Flags::from_bits_truncate(val as u8);
+
+                // println!("POPF");
+            }
+            0xD0 => {
+                let addr = hhll as usize;
+                let rgb = self.memory[addr..addr + 48].to_vec();
+                self.palette.load(&rgb);
+
+                // println!("PAL {:#X}", hhll);
+            }
             _ => {
                 panic!("Unknown opcode: {:#x} instr: 0x{}",
                        opcode,
@@ -390,12 +842,43 @@ impl CHIP16 {
 
         State::Continue
     }
+
+    fn push_u16(&mut self, val: u16) {
+        let mut buf = [0u8; 2];
+        LittleEndian::write_u16(&mut buf, val);
+
+        let addr = self.sp as usize;
+        self.memory[addr..addr + 2].copy_from_slice(&buf);
+        self.sp = self.sp.wrapping_add(2);
+    }
+
+    fn pop_u16(&mut self) -> u16 {
+        self.sp = self.sp.wrapping_sub(2);
+
+        let addr = self.sp as usize;
+        LittleEndian::read_u16(&self.memory[addr..addr + 2])
+    }
+
+    fn update_logic_flags(&mut self, res: i16) {
+        if res == 0 {
+            self.flags |= ZERO;
+        } else {
+            self.flags &= !ZERO;
+        }
+
+        if res < 0 {
+            self.flags |= NEGATIVE;
+        } else {
+            self.flags &= !NEGATIVE;
+        }
+    }
 }
 
 const WIDTH: usize = 320;
 const HEIGHT: usize = 240;
 
-use minifb::{Key, Scale, WindowOptions};
+use minifb::{Key, KeyRepeat, Scale, WindowOptions};
+use std::path::Path;
 use std::thread;
 use std::time::Duration;
 use std::sync::{Arc, Mutex};
@@ -455,12 +938,7 @@ pub fn cpu_loop<F>(rate: u64, mut callback: F)
 }
 
 fn main() {
-    let mut file = File::open("Ball.c16").unwrap();
-    let mut cartridge: Vec<u8> = Vec::new();
-    file.read_to_end(&mut cartridge).unwrap();
-
-    let header: &CH16Header = &cartridge[..16].into();
-    let cart = &cartridge[16..];
+    let cart_path = Path::new("Ball.c16");
 
     let buffer = Arc::new(Mutex::new(vec![0; WIDTH * HEIGHT]));
 
@@ -473,14 +951,56 @@ fn main() {
             Err(err) => panic!("Unable to create window {}", err),
         };
 
-    let chip16 = Arc::new(Mutex::new(CHIP16::new(header, cart)));
+    let sound = Sound::new();
+    sound::run(sound.clone());
+
+    let chip16 = match cartridge::load_cartridge(cart_path, sound) {
+        Ok(chip) => Arc::new(Mutex::new(chip)),
+        Err(err) => panic!("failed to load cartridge {:?}: {:?}", cart_path, err),
+    };
 
     let cpu_arc = buffer.clone();
     let c_a = chip16.clone();
-    cpu_loop(1_000_000, move || c_a.lock().unwrap().cycle(&cpu_arc));
+    let mut debugger = Debugger::new();
+    // debugger.enable(); // uncomment to drop into the (chip16-dbg) prompt
+    cpu_loop(1_000_000, move || {
+        let at_breakpoint = debugger.before_cycle(&c_a.lock().unwrap());
+
+        match at_breakpoint {
+            State::Stop => State::Stop,
+            State::Continue => c_a.lock().unwrap().cycle(&cpu_arc),
+        }
+    });
 
     let c_b = chip16.clone();
+    let state_screen = buffer.clone();
+    let mut save_slot = 0;
+    let p1_keys = KeyMap::player_one();
+    let p2_keys = KeyMap::player_two();
     draw_loop(60, || {
+        {
+            let mut chip = c_b.lock().unwrap();
+            chip.write_controller(0, p1_keys.poll(&window));
+            chip.write_controller(1, p2_keys.poll(&window));
+        }
+
+        if window.is_key_pressed(Key::F5, KeyRepeat::No) {
+            let path = state::state_path_for(cart_path, save_slot);
+            save_slot = (save_slot + 1) % 10;
+
+            if let Err(err) = c_b.lock().unwrap().save_state(&path, &state_screen) {
+                println!("Failed to save state to {:?}: {}", path, err);
+            }
+        }
+
+        if window.is_key_pressed(Key::F9, KeyRepeat::No) {
+            if let Some(path) = state::latest_state_path(cart_path) {
+                if let Err(err) = c_b.lock().unwrap().load_state(&path, &state_screen) {
+                    println!("Failed to load state from {:?}: {}", path, err);
+                }
+            }
+        }
+
         if window.is_open() && !window.is_key_down(Key::Escape) {
             window.update_with_buffer(&buffer.lock().unwrap());
 
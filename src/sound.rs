@@ -0,0 +1,332 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const SAMPLE_RATE: u32 = 44100;
+
+/// Number of generated samples to have queued up before the output
+/// callback is allowed to start pulling from the buffer, so the first
+/// callback doesn't starve and pop the speaker.
+const PREBUFFER_SAMPLES: usize = SAMPLE_RATE as usize / 20;
+
+/// Hard cap on queued-but-undrained samples, so a stalled output
+/// callback can't grow the buffer (and audio latency) without bound.
+const MAX_BUFFERED_SAMPLES: usize = SAMPLE_RATE as usize / 2;
+
+/// Attack/decay/sustain/release step durations, in milliseconds, indexed
+/// by the 4-bit nibbles packed into the `AD` and `SR` registers.
+const DURATION_TABLE_MS: [u32; 16] =
+    [0, 2, 4, 6, 8, 10, 16, 22, 30, 43, 64, 100, 156, 250, 400, 625];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Waveform {
+    Triangle,
+    Sawtooth,
+    Square,
+    Noise,
+}
+
+impl Waveform {
+    fn from_nibble(n: u8) -> Waveform {
+        match n & 0x3 {
+            0 => Waveform::Triangle,
+            1 => Waveform::Sawtooth,
+            2 => Waveform::Square,
+            _ => Waveform::Noise,
+        }
+    }
+}
+
+/// Decoded `SNG AD, VT, SR` registers: how a note ramps up, holds, and
+/// decays, plus which waveform generates it.
+#[derive(Clone, Copy, Debug)]
+pub struct Envelope {
+    pub attack_ms: u32,
+    pub decay_ms: u32,
+    pub sustain_volume: u8,
+    pub sustain_ms: u32,
+    pub release_ms: u32,
+    pub waveform: Waveform,
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope {
+        Envelope {
+            attack_ms: 0,
+            decay_ms: 0,
+            sustain_volume: 0,
+            sustain_ms: 0,
+            release_ms: 0,
+            waveform: Waveform::Triangle,
+        }
+    }
+}
+
+impl Envelope {
+    pub fn from_registers(ad: u8, vt: u8, sr: u8) -> Envelope {
+        Envelope {
+            attack_ms: DURATION_TABLE_MS[((ad & 0xF0) >> 4) as usize],
+            decay_ms: DURATION_TABLE_MS[(ad & 0x0F) as usize],
+            sustain_volume: (vt & 0xF0) >> 4,
+            waveform: Waveform::from_nibble(vt & 0x0F),
+            sustain_ms: DURATION_TABLE_MS[((sr & 0xF0) >> 4) as usize],
+            release_ms: DURATION_TABLE_MS[(sr & 0x0F) as usize],
+        }
+    }
+
+    fn peak_volume(&self) -> f32 {
+        i16::max_value() as f32
+    }
+
+    fn sustain_level(&self) -> f32 {
+        self.peak_volume() * (self.sustain_volume as f32 / 15.0)
+    }
+}
+
+struct Note {
+    freq_hz: f32,
+    duration_ms: u32,
+    envelope: Envelope,
+    phase: f32,
+    sample_index: u64,
+    rng: u32,
+}
+
+impl Note {
+    fn new(freq_hz: u16, duration_ms: u32, envelope: Envelope) -> Note {
+        Note {
+            freq_hz: freq_hz as f32,
+            duration_ms: duration_ms,
+            envelope: envelope,
+            phase: 0.0,
+            sample_index: 0,
+            rng: 0xACE1,
+        }
+    }
+
+    fn waveform_sample(&mut self) -> f32 {
+        let value = match self.envelope.waveform {
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sawtooth => self.phase * 2.0 - 1.0,
+            Waveform::Triangle => {
+                if self.phase < 0.5 {
+                    self.phase * 4.0 - 1.0
+                } else {
+                    3.0 - self.phase * 4.0
+                }
+            }
+            Waveform::Noise => {
+                // Classic 16-bit Fibonacci LFSR; cheap pseudo-random noise.
+                let bit = (self.rng ^ (self.rng >> 2) ^ (self.rng >> 3) ^ (self.rng >> 5)) & 1;
+                self.rng = (self.rng >> 1) | (bit << 15);
+                (self.rng as f32 / 0x8000 as f32) * 2.0 - 1.0
+            }
+        };
+
+        self.phase += self.freq_hz / SAMPLE_RATE as f32;
+        self.phase -= self.phase.floor();
+
+        value
+    }
+
+    /// Linear ADSR envelope volume at the current sample index.
+    fn envelope_volume(&self) -> f32 {
+        let elapsed_ms = (self.sample_index * 1000 / SAMPLE_RATE as u64) as f32;
+        let e = &self.envelope;
+        let peak = e.peak_volume();
+        let sustain = e.sustain_level();
+
+        if elapsed_ms < e.attack_ms as f32 {
+            if e.attack_ms == 0 {
+                peak
+            } else {
+                peak * (elapsed_ms / e.attack_ms as f32)
+            }
+        } else if elapsed_ms < (e.attack_ms + e.decay_ms) as f32 {
+            let t = elapsed_ms - e.attack_ms as f32;
+            if e.decay_ms == 0 {
+                sustain
+            } else {
+                peak - (peak - sustain) * (t / e.decay_ms as f32)
+            }
+        } else if elapsed_ms < (e.attack_ms + e.decay_ms + self.duration_ms) as f32 {
+            sustain
+        } else {
+            let t = elapsed_ms - (e.attack_ms + e.decay_ms + self.duration_ms) as f32;
+            if e.release_ms == 0 || t >= e.release_ms as f32 {
+                0.0
+            } else {
+                sustain - sustain * (t / e.release_ms as f32)
+            }
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        let total_ms = self.envelope.attack_ms + self.envelope.decay_ms + self.duration_ms +
+            self.envelope.release_ms;
+        self.sample_index * 1000 / SAMPLE_RATE as u64 >= total_ms as u64
+    }
+
+    fn next_sample(&mut self) -> i16 {
+        let volume = self.envelope_volume();
+        let sample = self.waveform_sample() * volume;
+        self.sample_index += 1;
+        sample as i16
+    }
+}
+
+struct OnePoleFilter {
+    alpha: f32,
+    prev_in: i16,
+    prev_out: i16,
+}
+
+impl OnePoleFilter {
+    fn new(alpha: f32) -> OnePoleFilter {
+        OnePoleFilter { alpha: alpha, prev_in: 0, prev_out: 0 }
+    }
+
+    fn low_pass(&mut self, input: i16) -> i16 {
+        let out = self.prev_out as f32 + ((input as f32 - self.prev_out as f32) * self.alpha);
+        self.prev_out = clamp_i16(out);
+        self.prev_out
+    }
+
+    fn high_pass(&mut self, input: i16) -> i16 {
+        let out = self.alpha * (self.prev_out as f32 + input as f32 - self.prev_in as f32);
+        self.prev_in = input;
+        self.prev_out = clamp_i16(out);
+        self.prev_out
+    }
+}
+
+fn clamp_i16(v: f32) -> i16 {
+    if v > i16::max_value() as f32 {
+        i16::max_value()
+    } else if v < i16::min_value() as f32 {
+        i16::min_value()
+    } else {
+        v as i16
+    }
+}
+
+/// Handle shared between the CPU thread (which triggers `SND0-3`/`SNG`)
+/// and the audio generator/output threads spawned by `run`.
+#[derive(Clone)]
+pub struct Sound {
+    note: Arc<Mutex<Option<Note>>>,
+    buffer: Arc<Mutex<VecDeque<i16>>>,
+}
+
+impl Sound {
+    pub fn new() -> Sound {
+        Sound {
+            note: Arc::new(Mutex::new(None)),
+            buffer: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn play(&self, freq_hz: u16, duration_ms: u32, envelope: Envelope) {
+        *self.note.lock().unwrap() = Some(Note::new(freq_hz, duration_ms, envelope));
+    }
+
+    pub fn stop(&self) {
+        *self.note.lock().unwrap() = None;
+    }
+
+    fn generate(&self, lpf: &mut OnePoleFilter, hpf: &mut OnePoleFilter) {
+        let mut note = self.note.lock().unwrap();
+
+        let raw = match *note {
+            Some(ref mut n) if !n.is_finished() => n.next_sample(),
+            Some(_) => {
+                *note = None;
+                0
+            }
+            None => 0,
+        };
+
+        let filtered = hpf.high_pass(lpf.low_pass(raw));
+
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.push_back(filtered);
+
+        // The output callback may stall (no device, blocked thread, ...);
+        // cap the backlog instead of letting it grow without bound.
+        while buffer.len() > MAX_BUFFERED_SAMPLES {
+            buffer.pop_front();
+        }
+    }
+
+    fn pop_sample(&self) -> Option<i16> {
+        self.buffer.lock().unwrap().pop_front()
+    }
+
+    fn buffered_len(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+}
+
+/// Spawns the sample generator thread, using the same fixed-rate
+/// accumulator loop as `cpu_loop`/`draw_loop`, and the audio output
+/// thread that drains it into the sound card.
+pub fn run(sound: Sound) {
+    let gen_sound = sound.clone();
+    thread::spawn(move || {
+        let mut lpf = OnePoleFilter::new(0.35);
+        let mut hpf = OnePoleFilter::new(0.995);
+
+        let mut accumulator = 0;
+        let mut previous_clock = clock_ticks::precise_time_ns();
+        let rate = 1_000_000_000 / SAMPLE_RATE as u64;
+
+        loop {
+            let now = clock_ticks::precise_time_ns();
+            accumulator += now - previous_clock;
+            previous_clock = now;
+
+            while accumulator >= rate {
+                gen_sound.generate(&mut lpf, &mut hpf);
+                accumulator -= rate;
+            }
+
+            thread::sleep(::std::time::Duration::from_nanos(rate));
+        }
+    });
+
+    thread::spawn(move || {
+        let endpoint = cpal::get_default_endpoint().expect("no audio output device available");
+        let format = endpoint.get_supported_formats_list()
+            .unwrap()
+            .next()
+            .expect("no supported audio format");
+
+        let event_loop = cpal::EventLoop::new();
+        let voice_id = event_loop.build_voice(&endpoint, &format).unwrap();
+        event_loop.play(voice_id);
+
+        let channels = format.channels.len();
+        let mut started = false;
+
+        event_loop.run(move |_voice_id, buffer| {
+            if !started {
+                if sound.buffered_len() < PREBUFFER_SAMPLES {
+                    return;
+                }
+                started = true;
+            }
+
+            match buffer {
+                cpal::UnknownTypeBuffer::I16(mut buffer) => {
+                    for frame in buffer.chunks_mut(channels) {
+                        let value = sound.pop_sample().unwrap_or(0);
+                        for out in frame.iter_mut() {
+                            *out = value;
+                        }
+                    }
+                }
+                _ => (),
+            }
+        });
+    });
+}
@@ -0,0 +1,152 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use {CHIP16, Color, Flags, MEMORY};
+
+const STATE_EXT: &'static str = "state";
+
+impl CHIP16 {
+    /// Dumps the full machine state (memory, registers, flags, sprite
+    /// settings, the loaded palette and the current framebuffer) to
+    /// `path` in a fixed binary layout so it can be restored byte-for-byte
+    /// with `load_state`.
+    pub fn save_state(&self, path: &Path, screen: &Arc<Mutex<Vec<u32>>>) -> io::Result<()> {
+        let mut file = try!(File::create(path));
+
+        try!(file.write_all(&self.memory));
+
+        let mut buf16 = [0u8; 2];
+        LittleEndian::write_u16(&mut buf16, self.pc);
+        try!(file.write_all(&buf16));
+        LittleEndian::write_u16(&mut buf16, self.sp);
+        try!(file.write_all(&buf16));
+
+        for r in self.regs.iter() {
+            LittleEndian::write_i16(&mut buf16, *r);
+            try!(file.write_all(&buf16));
+        }
+
+        try!(file.write_all(&[self.flags.bits()]));
+        try!(file.write_all(&[self.bg.clone() as u8]));
+        try!(file.write_all(&[self.fg.clone() as u8]));
+        try!(file.write_all(&[self.spritew]));
+        try!(file.write_all(&[self.spriteh]));
+        try!(file.write_all(&[self.vblank as u8]));
+
+        let mut buf32 = [0u8; 4];
+        for c in self.palette.colors.iter() {
+            LittleEndian::write_u32(&mut buf32, *c);
+            try!(file.write_all(&buf32));
+        }
+
+        let buff = screen.lock().unwrap();
+        for px in buff.iter() {
+            LittleEndian::write_u32(&mut buf32, *px);
+            try!(file.write_all(&buf32));
+        }
+
+        Ok(())
+    }
+
+    /// Restores a machine state previously written by `save_state`,
+    /// overwriting memory, registers, flags, sprite settings, the
+    /// palette and the framebuffer in place.
+    pub fn load_state(&mut self, path: &Path, screen: &Arc<Mutex<Vec<u32>>>) -> io::Result<()> {
+        let mut file = try!(File::open(path));
+        let mut data = Vec::new();
+        try!(file.read_to_end(&mut data));
+
+        let mut buff = screen.lock().unwrap();
+
+        let expected_len = MEMORY + 2 + 2 + self.regs.len() * 2 + 6 +
+            self.palette.colors.len() * 4 + buff.len() * 4;
+        if data.len() != expected_len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                       format!("corrupt or truncated save state: expected {} \
+                                                bytes, found {}",
+                                               expected_len,
+                                               data.len())));
+        }
+
+        let mut offset = 0;
+
+        self.memory.copy_from_slice(&data[offset..offset + MEMORY]);
+        offset += MEMORY;
+
+        self.pc = LittleEndian::read_u16(&data[offset..offset + 2]);
+        offset += 2;
+        self.sp = LittleEndian::read_u16(&data[offset..offset + 2]);
+        offset += 2;
+
+        for r in self.regs.iter_mut() {
+            *r = LittleEndian::read_i16(&data[offset..offset + 2]);
+            offset += 2;
+        }
+
+        self.flags = Flags::from_bits_truncate(data[offset]);
+        offset += 1;
+
+        self.bg = Color::from(data[offset]);
+        offset += 1;
+        self.fg = Color::from(data[offset]);
+        offset += 1;
+
+        self.spritew = data[offset];
+        offset += 1;
+        self.spriteh = data[offset];
+        offset += 1;
+
+        self.vblank = data[offset] != 0;
+        offset += 1;
+
+        for c in self.palette.colors.iter_mut() {
+            *c = LittleEndian::read_u32(&data[offset..offset + 4]);
+            offset += 4;
+        }
+
+        for px in buff.iter_mut() {
+            *px = LittleEndian::read_u32(&data[offset..offset + 4]);
+            offset += 4;
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds the save-slot path for a given cartridge, e.g. `Ball.0.state`.
+pub fn state_path_for(cart_path: &Path, slot: usize) -> PathBuf {
+    let mut path = cart_path.to_path_buf();
+    path.set_extension(format!("{}.{}", slot, STATE_EXT));
+    path
+}
+
+/// Finds the most recently *modified* `.state` file for a cartridge,
+/// rather than the highest numbered slot, so "load latest" Just Works
+/// regardless of which slot was saved to last.
+pub fn latest_state_path(cart_path: &Path) -> Option<PathBuf> {
+    let dir = cart_path.parent().unwrap_or(Path::new("."));
+
+    let stem = match cart_path.file_stem() {
+        Some(s) => s.to_string_lossy().into_owned(),
+        None => return None,
+    };
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return None,
+    };
+
+    entries.filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|p| {
+            p.extension().map_or(false, |ext| ext == STATE_EXT) &&
+            p.file_stem()
+                .map(|s| s.to_string_lossy().starts_with(stem.as_str()))
+                .unwrap_or(false)
+        })
+        .max_by_key(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+}